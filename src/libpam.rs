@@ -25,26 +25,59 @@ impl PamError {
 mod private {
     pub trait Sealed {}
     impl Sealed for super::Pam {}
+    impl Sealed for super::PamTty {}
+    impl Sealed for super::PamService {}
+    impl Sealed for super::PamRHost {}
+    impl Sealed for super::PamRUser {}
+    impl Sealed for super::PamUser {}
+    impl Sealed for super::PamAuthTok {}
+    impl Sealed for super::PamOldAuthtok {}
 }
 
-impl Pam {
-    // End users should call the item specific methods
-    fn get_cstr_item(&self, item_type: PamItemType) -> PamResult<Option<&CStr>> {
-        match item_type {
-            PamItemType::CONV | PamItemType::FAIL_DELAY | PamItemType::XAUTHDATA => {
-                panic!("Error, get_cstr_item can only be used with pam item returning c-strings")
-            }
-            _ => (),
-        }
-        let mut raw_item: *const c_void = ptr::null();
-        let r = unsafe { PamError::new(pam_get_item(self.0, item_type as c_int, &mut raw_item)) };
-        if raw_item.is_null() {
-            r.to_result(None)
-        } else {
-            // pam should keep the underlying token allocated during the lifetime of the module
-            r.to_result(Some(unsafe { CStr::from_ptr(raw_item as *const c_char) }))
-        }
-    }
+/// A PAM item that can be read and written through a plain C string via
+/// `pam_get_item`/`pam_set_item`. Sealed to the zero-sized marker types
+/// below, since `PAM_CONV`, `PAM_FAIL_DELAY` and `PAM_XAUTHDATA` carry
+/// structs rather than strings and can't go through this path; trying to
+/// use one of those is a compile error rather than a runtime panic.
+pub trait PamItem: private::Sealed {
+    const ITEM_TYPE: PamItemType;
+}
+
+/// Marker for `PAM_TTY`.
+pub struct PamTty;
+/// Marker for `PAM_SERVICE`.
+pub struct PamService;
+/// Marker for `PAM_RHOST`.
+pub struct PamRHost;
+/// Marker for `PAM_RUSER`.
+pub struct PamRUser;
+/// Marker for `PAM_USER`.
+pub struct PamUser;
+/// Marker for `PAM_AUTHTOK`.
+pub struct PamAuthTok;
+/// Marker for `PAM_OLDAUTHTOK`.
+pub struct PamOldAuthtok;
+
+impl PamItem for PamTty {
+    const ITEM_TYPE: PamItemType = PamItemType::TTY;
+}
+impl PamItem for PamService {
+    const ITEM_TYPE: PamItemType = PamItemType::SERVICE;
+}
+impl PamItem for PamRHost {
+    const ITEM_TYPE: PamItemType = PamItemType::RHOST;
+}
+impl PamItem for PamRUser {
+    const ITEM_TYPE: PamItemType = PamItemType::RUSER;
+}
+impl PamItem for PamUser {
+    const ITEM_TYPE: PamItemType = PamItemType::USER;
+}
+impl PamItem for PamAuthTok {
+    const ITEM_TYPE: PamItemType = PamItemType::AUTHTOK;
+}
+impl PamItem for PamOldAuthtok {
+    const ITEM_TYPE: PamItemType = PamItemType::OLDAUTHTOK;
 }
 
 /// Extension trait over `Pam`, usually provided by the `libpam` shared library.
@@ -75,13 +108,42 @@ pub trait PamLibExt: private::Sealed {
     /// Get the remote username.
     fn get_ruser(&self) -> PamResult<Option<&CStr>>;
 
+    /// Get the controlling tty.
+    fn get_tty(&self) -> PamResult<Option<&CStr>>;
+
+    /// Get the service name.
+    fn get_service(&self) -> PamResult<Option<&CStr>>;
+
+    /// Get an arbitrary PAM item through the generic, sealed `PamItem` path.
+    /// Every `PamItem` type also has a dedicated getter (`get_rhost`,
+    /// `get_tty`, ...), each a thin wrapper over this method.
+    fn get_item<I: PamItem>(&self) -> PamResult<Option<&CStr>>;
+
+    /// Set an arbitrary PAM item through the generic, sealed `PamItem` path.
+    /// Prefer `set_authtok` for `PAM_AUTHTOK`; this is here for items that
+    /// don't have a dedicated setter.
+    fn set_item<I: PamItem>(&self, value: &CStr) -> PamResult<()>;
+
     /// Prompt the user for custom input.
     /// Returns PamError::SERVICE_ERR if the prompt contains any null byte
     fn conv(&self, prompt: Option<&str>, style: PamMsgStyle) -> PamResult<Option<&CStr>>;
 
+    /// Send several messages to the conversation callback in a single
+    /// round-trip, e.g. an info banner followed by two prompts. On success,
+    /// returns one response slot per message, in order; messages styled
+    /// `TEXT_INFO` or `ERROR_MSG` have no response and yield `None` in their
+    /// slot.
+    /// Returns PamError::SERVICE_ERR if any message contains a null byte,
+    /// or if no conversation function is available (no `PAM_CONV` item set).
+    fn conv_multi(&self, messages: &[(PamMsgStyle, &str)]) -> PamResult<Vec<Option<CString>>>;
+
     /// Get a variable from the pam environment list.
     fn getenv(&self, name: &str) -> PamResult<Option<&CStr>>;
 
+    /// Snapshot the full pam environment list, i.e. the variables PAM will
+    /// export into the user's session, as owned `(name, value)` pairs.
+    fn envlist(&self) -> PamResult<Vec<(CString, CString)>>;
+
     /// Put a variable in the pam environment list.
     /// `name_value` takes for form documented in pam_putent(3) :
     ///
@@ -91,6 +153,33 @@ pub trait PamLibExt: private::Sealed {
     fn putenv(&self, name_value: &str) -> PamResult<()>;
 
     fn syslog(&self, priority: Severity, message: &str) -> PamResult<()>;
+
+    /// Log a machine-parseable audit record, e.g. `host="1.2.3.4"
+    /// outcome="denied"`, without risking a format-string or quoting
+    /// injection from attacker-controlled field values.
+    /// Returns PamError::SERVICE_ERR if any key or value contains a null byte.
+    fn syslog_fields(&self, priority: Severity, fields: &[(&str, &str)]) -> PamResult<()>;
+
+    /// Stash an arbitrary value under `name` so it can be retrieved later in
+    /// the same or a subsequent PAM call (e.g. pass a decrypted token from
+    /// `pam_sm_authenticate` to `pam_sm_setcred`). The value is owned by PAM
+    /// until the handle is torn down or another call replaces it under the
+    /// same name, at which point it is dropped.
+    /// Returns PamError::SERVICE_ERR if `name` contains any null byte.
+    fn send_data<T: Send + Clone + 'static>(&self, name: &str, data: T) -> PamResult<()>;
+
+    /// Retrieve a value previously stored with `send_data`, cloning it so
+    /// the copy owned by PAM is left untouched. Returns `None` if nothing
+    /// was stored under `name`.
+    /// Returns PamError::SERVICE_ERR if `name` contains any null byte.
+    fn retrieve_data<T: Send + Clone + 'static>(&self, name: &str) -> PamResult<Option<T>>;
+
+    /// Byte-oriented variant of `send_data`, for modules that would rather
+    /// not name a concrete type at the storage site.
+    fn send_bytes(&self, name: &str, data: &[u8]) -> PamResult<()>;
+
+    /// Byte-oriented variant of `retrieve_data`.
+    fn retrieve_bytes(&self, name: &str) -> PamResult<Option<Vec<u8>>>;
 }
 
 impl From<NulError> for PamError {
@@ -122,15 +211,15 @@ impl PamLibExt for Pam {
     }
 
     fn get_cached_user(&self) -> PamResult<Option<&CStr>> {
-        self.get_cstr_item(PamItemType::USER)
+        self.get_item::<PamUser>()
     }
 
     fn get_cached_authtok(&self) -> PamResult<Option<&CStr>> {
-        self.get_cstr_item(PamItemType::AUTHTOK)
+        self.get_item::<PamAuthTok>()
     }
 
     fn get_cached_oldauthtok(&self) -> PamResult<Option<&CStr>> {
-        self.get_cstr_item(PamItemType::OLDAUTHTOK)
+        self.get_item::<PamOldAuthtok>()
     }
 
     fn get_authtok(&self, prompt: Option<&str>) -> PamResult<Option<&CStr>> {
@@ -156,21 +245,38 @@ impl PamLibExt for Pam {
     }
 
     fn set_authtok(&self, authtok: &CString) -> PamResult<()> {
-        unsafe {
-            set_item(
-                self.0,
-                PamItemType::AUTHTOK,
-                authtok.as_ptr() as *const c_void,
-            )
-        }
+        self.set_item::<PamAuthTok>(authtok)
     }
 
     fn get_rhost(&self) -> PamResult<Option<&CStr>> {
-        self.get_cstr_item(PamItemType::RHOST)
+        self.get_item::<PamRHost>()
     }
 
     fn get_ruser(&self) -> PamResult<Option<&CStr>> {
-        self.get_cstr_item(PamItemType::RUSER)
+        self.get_item::<PamRUser>()
+    }
+
+    fn get_tty(&self) -> PamResult<Option<&CStr>> {
+        self.get_item::<PamTty>()
+    }
+
+    fn get_service(&self) -> PamResult<Option<&CStr>> {
+        self.get_item::<PamService>()
+    }
+
+    fn get_item<I: PamItem>(&self) -> PamResult<Option<&CStr>> {
+        let mut raw_item: *const c_void = ptr::null();
+        let r = unsafe { PamError::new(pam_get_item(self.0, I::ITEM_TYPE as c_int, &mut raw_item)) };
+        if raw_item.is_null() {
+            r.to_result(None)
+        } else {
+            // pam should keep the underlying token allocated during the lifetime of the module
+            r.to_result(Some(unsafe { CStr::from_ptr(raw_item as *const c_char) }))
+        }
+    }
+
+    fn set_item<I: PamItem>(&self, value: &CStr) -> PamResult<()> {
+        unsafe { set_raw_item(self.0, I::ITEM_TYPE, value.as_ptr() as *const c_void) }
     }
 
     fn conv(&self, prompt: Option<&str>, style: PamMsgStyle) -> PamResult<Option<&CStr>> {
@@ -215,6 +321,83 @@ impl PamLibExt for Pam {
         }
     }
 
+    fn conv_multi(&self, messages: &[(PamMsgStyle, &str)]) -> PamResult<Vec<Option<CString>>> {
+        let mut conv_pointer: *const c_void = ptr::null();
+        let r = unsafe {
+            PamError::new(pam_get_item(
+                self.0,
+                PamItemType::CONV as c_int,
+                &mut conv_pointer,
+            ))
+        };
+
+        if r != PamError::SUCCESS {
+            return Err(r);
+        }
+
+        if conv_pointer.is_null() {
+            return Err(PamError::SERVICE_ERR);
+        }
+
+        let conv = unsafe { &*(conv_pointer as *const PamConv) };
+
+        // Build the owned prompt strings first so a null byte anywhere
+        // aborts before we touch the conversation callback.
+        let msg_cstrs = messages
+            .iter()
+            .map(|&(_, text)| CString::new(text))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pam_messages: Vec<PamMessage> = messages
+            .iter()
+            .zip(&msg_cstrs)
+            .map(|(&(style, _), text)| PamMessage {
+                msg_style: style,
+                msg: text.as_ptr(),
+            })
+            .collect();
+        // Linux-PAM's `conv` callback takes `const struct pam_message **`,
+        // i.e. a pointer to an array of pointers to messages, not an array
+        // of messages.
+        let msg_ptrs: Vec<*const PamMessage> =
+            pam_messages.iter().map(|m| m as *const PamMessage).collect();
+
+        let mut resp_ptr: *mut PamResponse = ptr::null_mut();
+        let result = conv.cb.map(|cb| {
+            PamError::new(cb(
+                msg_ptrs.len() as c_int,
+                msg_ptrs.as_ptr() as *mut *const PamMessage,
+                &mut resp_ptr,
+                conv.appdata_ptr,
+            ))
+        });
+
+        match result {
+            Some(PamError::SUCCESS) => {
+                let mut responses = Vec::with_capacity(messages.len());
+                for (i, &(style, _)) in messages.iter().enumerate() {
+                    let slot = unsafe { &*resp_ptr.add(i) };
+                    // TEXT_INFO/ERROR_MSG don't prompt for anything, so
+                    // their slot is always None regardless of what the
+                    // conversation callback actually set it to.
+                    let owned = match style {
+                        PamMsgStyle::TEXT_INFO | PamMsgStyle::ERROR_MSG => None,
+                        _ => slot
+                            .resp
+                            .map(|r| unsafe { CStr::from_ptr(r.as_ptr()) }.to_owned()),
+                    };
+                    if let Some(r) = slot.resp {
+                        unsafe { libc::free(r.as_ptr() as *mut c_void) };
+                    }
+                    responses.push(owned);
+                }
+                unsafe { libc::free(resp_ptr as *mut c_void) };
+                Ok(responses)
+            }
+            Some(ret) => Err(ret),
+            None => Err(PamError::SERVICE_ERR),
+        }
+    }
+
     fn getenv(&self, name: &str) -> PamResult<Option<&CStr>> {
         let cname = CString::new(name)?;
         let cenv = unsafe { pam_getenv(self.0, cname.as_ptr()) };
@@ -226,6 +409,34 @@ impl PamLibExt for Pam {
         }
     }
 
+    fn envlist(&self) -> PamResult<Vec<(CString, CString)>> {
+        let raw = unsafe { pam_getenvlist(self.0) };
+        if raw.is_null() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        let mut cursor = raw;
+        unsafe {
+            while !(*cursor).is_null() {
+                let entry = CStr::from_ptr(*cursor);
+                if let Some(eq) = entry.to_bytes().iter().position(|&b| b == b'=') {
+                    let (name, value) = entry.to_bytes().split_at(eq);
+                    entries.push((
+                        CString::new(name).expect("no interior NUL before '=' in pam env entry"),
+                        CString::new(&value[1..])
+                            .expect("no interior NUL in pam env entry value"),
+                    ));
+                }
+                libc::free(*cursor as *mut c_void);
+                cursor = cursor.add(1);
+            }
+            libc::free(raw as *mut c_void);
+        }
+
+        Ok(entries)
+    }
+
     fn putenv(&self, name_value: &str) -> PamResult<()> {
         let cenv = CString::new(name_value)?;
         unsafe { PamError::new(pam_putenv(self.0, cenv.as_ptr())).to_result(()) }
@@ -244,9 +455,97 @@ impl PamLibExt for Pam {
         }
         Ok(())
     }
+
+    fn syslog_fields(&self, priority: Severity, fields: &[(&str, &str)]) -> PamResult<()> {
+        let mut line = String::new();
+        for (i, (key, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                line.push(' ');
+            }
+            push_escaped_field(&mut line, key)?;
+            line.push_str("=\"");
+            push_escaped_field(&mut line, value)?;
+            line.push('"');
+        }
+        self.syslog(priority, &line)
+    }
+
+    fn send_data<T: Send + Clone + 'static>(&self, name: &str, data: T) -> PamResult<()> {
+        let cname = CString::new(name)?;
+        let boxed = Box::into_raw(Box::new(data));
+        let r = unsafe {
+            PamError::new(pam_set_data(
+                self.0,
+                cname.as_ptr(),
+                boxed as *mut c_void,
+                Some(cleanup_data::<T>),
+            ))
+        };
+        if r != PamError::SUCCESS {
+            // PAM only takes ownership (and will run the cleanup
+            // trampoline) on success; on failure it neither stores the
+            // pointer nor calls the cleanup, so reclaim it ourselves.
+            unsafe {
+                drop(Box::from_raw(boxed));
+            }
+        }
+        r.to_result(())
+    }
+
+    fn retrieve_data<T: Send + Clone + 'static>(&self, name: &str) -> PamResult<Option<T>> {
+        let cname = CString::new(name)?;
+        let mut raw_data: *const c_void = ptr::null();
+        let r = unsafe { PamError::new(pam_get_data(self.0, cname.as_ptr(), &mut raw_data)) };
+        // Unlike pam_get_item, pam_get_data reports an absent key as a
+        // failure status (PAM_NO_MODULE_DATA) rather than SUCCESS with a
+        // null pointer, so a null pointer always means "nothing stored"
+        // regardless of `r`.
+        if raw_data.is_null() {
+            Ok(None)
+        } else {
+            r.to_result(Some(unsafe { &*(raw_data as *const T) }.clone()))
+        }
+    }
+
+    fn send_bytes(&self, name: &str, data: &[u8]) -> PamResult<()> {
+        self.send_data(name, data.to_vec())
+    }
+
+    fn retrieve_bytes(&self, name: &str) -> PamResult<Option<Vec<u8>>> {
+        self.retrieve_data(name)
+    }
+}
+
+// Appends `field` to `line`, quote- and control-character-escaped so it
+// can't forge a neighbouring `key="value"` pair or split the syslog line.
+// A literal null byte can't be escaped away (`pam_syslog` takes a plain
+// C string), so it's rejected outright rather than silently rewritten.
+fn push_escaped_field(line: &mut String, field: &str) -> PamResult<()> {
+    for c in field.chars() {
+        match c {
+            '\0' => return Err(PamError::SERVICE_ERR),
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            c if c.is_control() => line.push_str(&format!("\\x{:02x}", c as u32)),
+            _ => line.push(c),
+        }
+    }
+    Ok(())
+}
+
+// Reconstructs and drops the `Box<T>` stashed by `send_data` once PAM is
+// done with it (handle teardown, or replacement under the same name).
+extern "C" fn cleanup_data<T>(_pamh: PamHandle, data: *mut c_void, _error_status: c_int) {
+    if !data.is_null() {
+        unsafe {
+            drop(Box::from_raw(data as *mut T));
+        }
+    }
 }
 
-unsafe fn set_item(pamh: PamHandle, item_type: PamItemType, item: *const c_void) -> PamResult<()> {
+unsafe fn set_raw_item(pamh: PamHandle, item_type: PamItemType, item: *const c_void) -> PamResult<()> {
     PamError::new(pam_set_item(pamh, item_type as c_int, item)).to_result(())
 }
 
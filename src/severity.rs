@@ -1,6 +1,8 @@
 pub enum Severity {
     Critical,
     Error,
+    Warning,
+    Notice,
     Info,
     Debug,
 }
@@ -10,6 +12,8 @@ impl Severity {
         match self {
             Self::Critical => libc::LOG_CRIT,
             Self::Error => libc::LOG_ERR,
+            Self::Warning => libc::LOG_WARNING,
+            Self::Notice => libc::LOG_NOTICE,
             Self::Info => libc::LOG_INFO,
             Self::Debug => libc::LOG_DEBUG,
         }